@@ -1,82 +1,169 @@
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
 use std::{env, path::Path};
 
-use crate::job::Job;
+use crate::job::{Job, Status};
+use std::str::FromStr;
 
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+// Caps how many concurrent `serve` worker threads can hold a connection at
+// once, so a burst of requests blocks briefly on checkout instead of an
+// unbounded number of threads piling up behind SQLite's single writer.
+const DB_POOL_SIZE: u32 = 16;
+
+#[derive(Clone)]
 pub struct DB {
-    conn: Connection,
+    pool: DbPool,
 }
 
+// Ordered list of schema migrations. The migration at index `N` moves the
+// database from schema version `N` to `N + 1`. `DB::new` reads the current
+// version out of `PRAGMA user_version` and replays every migration at or
+// past that index, so existing databases pick up new columns/tables instead
+// of silently drifting out of sync with the code.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE jobs (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL,
+        date DATE NOT NULL
+    );
+    "#,
+    r#"ALTER TABLE jobs ADD COLUMN status TEXT NOT NULL DEFAULT 'saved';"#,
+    r#"
+    CREATE VIRTUAL TABLE jobs_fts USING fts5(title, description, content='jobs', content_rowid='id');
+
+    INSERT INTO jobs_fts(rowid, title, description) SELECT id, title, description FROM jobs;
+
+    CREATE TRIGGER jobs_ai AFTER INSERT ON jobs BEGIN
+        INSERT INTO jobs_fts(rowid, title, description) VALUES (new.id, new.title, new.description);
+    END;
+
+    CREATE TRIGGER jobs_ad AFTER DELETE ON jobs BEGIN
+        INSERT INTO jobs_fts(jobs_fts, rowid, title, description) VALUES('delete', old.id, old.title, old.description);
+    END;
+
+    CREATE TRIGGER jobs_au AFTER UPDATE ON jobs BEGIN
+        INSERT INTO jobs_fts(jobs_fts, rowid, title, description) VALUES('delete', old.id, old.title, old.description);
+        INSERT INTO jobs_fts(rowid, title, description) VALUES (new.id, new.title, new.description);
+    END;
+    "#,
+    r#"
+    UPDATE jobs
+    SET date = substr(date, 7, 4) || '-' || substr(date, 4, 2) || '-' || substr(date, 1, 2)
+    WHERE date LIKE '__-__-____';
+    "#,
+];
+
 impl DB {
     pub fn new() -> Self {
-        let pool = DB::get_db();
-        Self { conn: pool }
+        let pool = DB::build_pool();
+        let conn = pool.get().expect("Could not get connection from pool");
+        DB::run_migrations(&conn);
+        Self { pool }
+    }
+
+    pub fn get_conn(&self) -> PooledConnection<SqliteConnectionManager> {
+        self.pool.get().expect("Could not get connection from pool")
     }
 
-    pub fn get_conn(&self) -> &Connection {
-        &self.conn
+    pub fn try_get_conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, r2d2::Error> {
+        self.pool.get()
     }
 
     pub fn close(self) {
-        match self.conn.close() {
-            Ok(_) => (),
-            Err((conn, err)) => {
-                eprintln!("Error closing database connection: {}", err);
-                drop(conn);
-            }
-        }
+        // Pooled connections close themselves as they're dropped; nothing to
+        // flush explicitly.
     }
 
-    fn get_db() -> Connection {
+    pub fn path() -> String {
         // this will not work on windows.
         let home = env::var("HOME").expect("Could not get home directory.");
-        let db_path = format!("{}/.local/share/job_search/job_search.db", home);
+        format!("{}/.local/share/job_search/job_search.db", home)
+    }
 
-        let mut db_existed = true;
+    fn build_pool() -> DbPool {
+        let db_path = DB::path();
 
         if !Path::new(&db_path).exists() {
-            db_existed = false;
             println!("Creating database at {}", db_path);
             std::fs::create_dir_all(Path::new(&db_path).parent().unwrap()).unwrap();
             std::fs::File::create(&db_path).unwrap();
         }
 
-        let conn = Connection::open(&db_path).expect("Could not open database connection.");
+        let manager = SqliteConnectionManager::file(&db_path);
+        Pool::builder()
+            .max_size(DB_POOL_SIZE)
+            .build(manager)
+            .expect("Could not build database connection pool.")
+    }
 
-        if !db_existed {
-            DB::migrate_db(&conn);
-        }
-        conn
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |_| Ok(()),
+        )
+        .is_ok()
     }
 
-    fn migrate_db(conn: &Connection) {
-        let job_table = r#"
-        CREATE TABLE jobs (
-            id INTEGER PRIMARY KEY,
-            title TEXT NOT NULL,
-            description TEXT NOT NULL,
-            date DATE NOT NULL
-        );
-        "#;
-        conn.execute(job_table, []).unwrap();
+    fn run_migrations(conn: &Connection) {
+        let mut current_version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .expect("Could not read schema version");
+
+        // Databases created before this migration runner existed have no
+        // `user_version` set, but already have the `jobs` table from what is
+        // now migration 0. Adopt them at version 1 instead of replaying
+        // `CREATE TABLE jobs` and crashing on a pre-existing table.
+        if current_version == 0 && DB::table_exists(conn, "jobs") {
+            current_version = 1;
+            conn.pragma_update(None, "user_version", current_version)
+                .expect("Error stamping schema version");
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            let version = index as i32;
+            if version < current_version {
+                continue;
+            }
+
+            let tx = conn
+                .unchecked_transaction()
+                .expect("Could not start migration transaction");
+            tx.execute_batch(migration)
+                .unwrap_or_else(|e| panic!("Error applying migration {}: {}", version, e));
+            tx.pragma_update(None, "user_version", version + 1)
+                .expect("Error bumping schema version");
+            tx.commit().expect("Error committing migration");
+        }
     }
 
     pub fn drop_db(&self) {
-        let job_table = r#"DROP TABLE jobs;"#;
-        self.conn
-            .execute(job_table, [])
-            .map_err(|e| println!("Error dropping database: {}", e))
-            .unwrap();
-        DB::migrate_db(&self.conn);
+        let conn = self.get_conn();
+        conn.execute_batch(
+            r#"
+            DROP TABLE IF EXISTS jobs_fts;
+            DROP TABLE IF EXISTS jobs;
+            "#,
+        )
+        .map_err(|e| println!("Error dropping database: {}", e))
+        .unwrap();
+        conn.pragma_update(None, "user_version", 0)
+            .expect("Error resetting schema version");
+        DB::run_migrations(&conn);
     }
 }
 
-pub struct Queries<'a> {
-    conn: &'a Connection,
+pub struct Queries {
+    conn: PooledConnection<SqliteConnectionManager>,
 }
 
-impl<'a> Queries<'a> {
-    pub fn new(conn: &'a Connection) -> Self {
+impl Queries {
+    pub fn new(conn: PooledConnection<SqliteConnectionManager>) -> Self {
         Self { conn }
     }
 
@@ -93,7 +180,7 @@ impl<'a> Queries<'a> {
     pub fn list_jobs(&self) -> Vec<Job> {
         let mut rows = self
             .conn
-            .prepare("SELECT id, title, description, date FROM jobs")
+            .prepare("SELECT id, title, description, date, status FROM jobs")
             .expect("Error preparing query for listing jobs");
 
         let mut jobs: Vec<Job> = Vec::new();
@@ -105,6 +192,7 @@ impl<'a> Queries<'a> {
                     title: row.get(1)?,
                     description: row.get(2)?,
                     date: row.get(3)?,
+                    status: row.get(4)?,
                 })
             })
             .expect("Error listing jobs");
@@ -124,41 +212,80 @@ impl<'a> Queries<'a> {
         title: Option<String>,
         description: Option<String>,
         date: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+        status: Option<String>,
     ) -> Vec<Job> {
-        let mut query = String::from("SELECT id, title, description, date FROM jobs");
-        let mut args: Vec<String> = Vec::new();
+        let mut conditions: Vec<String> = Vec::new();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
         if let Some(title) = title {
             if !title.is_empty() {
-                args.push(format!("title LIKE '%{}%'", title));
+                conditions.push(format!("title LIKE ?{}", params.len() + 1));
+                params.push(Box::new(format!("%{}%", title)));
             }
         }
 
         if let Some(description) = description {
             if !description.is_empty() {
-                args.push(format!("description LIKE '%{}%'", description));
+                conditions.push(format!("description LIKE ?{}", params.len() + 1));
+                params.push(Box::new(format!("%{}%", description)));
             }
         }
 
         if let Some(date) = date {
             if !date.is_empty() {
-                let formatted_date = match chrono::NaiveDate::parse_from_str(&date, "%d-%m-%Y") {
-                    Ok(date) => date.format("%d-%m-%Y").to_string(),
-                    Err(_) => "".to_string(),
-                };
-                if !formatted_date.is_empty() {
-                    args.push(format!("date='{}'", formatted_date));
-                } else {
-                    eprintln!("Invalid date format. Please use the format dd-mm-yyyy.");
+                match chrono::NaiveDate::parse_from_str(&date, "%d-%m-%Y") {
+                    Ok(date) => {
+                        conditions.push(format!("date = ?{}", params.len() + 1));
+                        params.push(Box::new(date.format("%Y-%m-%d").to_string()));
+                    }
+                    Err(_) => eprintln!("Invalid date format. Please use the format dd-mm-yyyy."),
                 }
             }
         }
 
-        if args.len() > 0 {
-            query.push_str(" WHERE ");
-            query.push_str(&args.join(" AND "));
+        if let Some(after) = after {
+            if !after.is_empty() {
+                match chrono::NaiveDate::parse_from_str(&after, "%d-%m-%Y") {
+                    Ok(after) => {
+                        conditions.push(format!("date >= ?{}", params.len() + 1));
+                        params.push(Box::new(after.format("%Y-%m-%d").to_string()));
+                    }
+                    Err(_) => eprintln!("Invalid date format. Please use the format dd-mm-yyyy."),
+                }
+            }
+        }
+
+        if let Some(before) = before {
+            if !before.is_empty() {
+                match chrono::NaiveDate::parse_from_str(&before, "%d-%m-%Y") {
+                    Ok(before) => {
+                        conditions.push(format!("date <= ?{}", params.len() + 1));
+                        params.push(Box::new(before.format("%Y-%m-%d").to_string()));
+                    }
+                    Err(_) => eprintln!("Invalid date format. Please use the format dd-mm-yyyy."),
+                }
+            }
+        }
+
+        if let Some(status) = status {
+            if !status.is_empty() {
+                match Status::from_str(&status) {
+                    Ok(status) => {
+                        conditions.push(format!("status = ?{}", params.len() + 1));
+                        params.push(Box::new(status.to_string()));
+                    }
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
         }
 
+        let mut query = String::from("SELECT id, title, description, date, status FROM jobs");
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
         query.push_str(" ORDER BY date ASC");
 
         let mut rows = self
@@ -167,15 +294,18 @@ impl<'a> Queries<'a> {
             .map_err(|e| println!("Error preparing query for searching jobs: {}", e))
             .unwrap();
 
+        let params: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
         let mut jobs: Vec<Job> = Vec::new();
 
         let jobs_iter = rows
-            .query_map([], |row| {
+            .query_map(params.as_slice(), |row| {
                 Ok(Job {
                     id: row.get(0)?,
                     title: row.get(1)?,
                     description: row.get(2)?,
                     date: row.get(3)?,
+                    status: row.get(4)?,
                 })
             })
             .expect("Error searching jobs");
@@ -190,10 +320,123 @@ impl<'a> Queries<'a> {
         jobs
     }
 
+    pub fn fts_search(&self, query: &str) -> Vec<Job> {
+        let query = Queries::sanitize_fts_query(query);
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT jobs.id, jobs.title, jobs.description, jobs.date, jobs.status
+                 FROM jobs_fts
+                 JOIN jobs ON jobs.id = jobs_fts.rowid
+                 WHERE jobs_fts MATCH ?1
+                 ORDER BY rank",
+            )
+            .expect("Error preparing full-text search query");
+
+        let mut jobs: Vec<Job> = Vec::new();
+
+        let jobs_iter = match stmt.query_map([query], |row| {
+            Ok(Job {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                description: row.get(2)?,
+                date: row.get(3)?,
+                status: row.get(4)?,
+            })
+        }) {
+            Ok(jobs_iter) => jobs_iter,
+            Err(e) => {
+                eprintln!("Error running full-text search: {}", e);
+                return jobs;
+            }
+        };
+
+        for job in jobs_iter {
+            match job {
+                Ok(job) => jobs.push(job),
+                Err(e) => eprintln!("Error processing job row: {}", e),
+            }
+        }
+
+        jobs
+    }
+
+    // FTS5 gives special meaning to quotes and operators like `*`, `-` and
+    // `:`. Wrap every term in its own quoted phrase (doubling embedded quotes
+    // per FTS5's escaping rule) so user input is always treated as literal
+    // text instead of being parsed as query syntax that can fail at step
+    // time.
+    fn sanitize_fts_query(query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     pub fn remove_job(&self, id: i32) {
         let _ = self
             .conn
             .execute("DELETE FROM jobs WHERE id = ?", [id])
             .expect("Error removing job");
     }
+
+    pub fn add_jobs(&self, jobs: Vec<Job>, upsert: bool) {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .expect("Could not start import transaction");
+
+        if upsert {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO jobs (id, title, description, date, status) VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(id) DO UPDATE SET
+                        title = excluded.title,
+                        description = excluded.description,
+                        date = excluded.date,
+                        status = excluded.status",
+                )
+                .expect("Error preparing import query");
+            for job in jobs {
+                stmt.execute(rusqlite::params![
+                    job.id,
+                    job.title,
+                    job.description,
+                    job.date,
+                    job.status
+                ])
+                .expect("Error importing job");
+            }
+        } else {
+            // Without --upsert, re-importing a backup should duplicate rows
+            // rather than collide with the ids it was exported with, so let
+            // the id column autoincrement instead of reusing the exported one.
+            let mut stmt = tx
+                .prepare("INSERT INTO jobs (title, description, date, status) VALUES (?1, ?2, ?3, ?4)")
+                .expect("Error preparing import query");
+            for job in jobs {
+                stmt.execute(rusqlite::params![
+                    job.title,
+                    job.description,
+                    job.date,
+                    job.status
+                ])
+                .expect("Error importing job");
+            }
+        }
+
+        tx.commit().expect("Error committing import");
+    }
+
+    pub fn update_status(&self, id: i32, status: Status) {
+        let _ = self
+            .conn
+            .execute(
+                "UPDATE jobs SET status = ?1 WHERE id = ?2",
+                rusqlite::params![status, id],
+            )
+            .expect("Error updating job status");
+    }
 }
@@ -0,0 +1,67 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Saved,
+    Applied,
+    Interviewing,
+    Offer,
+    Rejected,
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "saved" => Ok(Status::Saved),
+            "applied" => Ok(Status::Applied),
+            "interviewing" => Ok(Status::Interviewing),
+            "offer" => Ok(Status::Offer),
+            "rejected" => Ok(Status::Rejected),
+            _ => Err(format!(
+                "\nInvalid status: {}. Valid statuses: saved, applied, interviewing, offer, rejected",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Status::Saved => "saved",
+            Status::Applied => "applied",
+            Status::Interviewing => "interviewing",
+            Status::Offer => "offer",
+            Status::Rejected => "rejected",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromSql for Status {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Status::from_str(value.as_str()?).map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for Status {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub date: String,
+    pub status: Status,
+}
@@ -7,14 +7,16 @@ mod job;
 use std::{
     collections::HashMap,
     fs::File,
-    io::{BufRead, BufReader, BufWriter, Read},
+    io::{self, BufRead, BufReader, Read},
     net::{TcpListener, TcpStream},
     str::FromStr,
+    thread,
 };
 
 use chrono::{Local, NaiveDate};
 use clap::{Args, Parser, Subcommand};
 use db::DB;
+use job::Status;
 use prettytable::{csv, Cell, Row, Table};
 use std::io::Write;
 
@@ -39,12 +41,31 @@ enum Commands {
     Search(SearchArgs),
     #[command(about = "Remove a job by its id")]
     Remove { id: i32 },
+    #[command(about = "Update the pipeline status of a job")]
+    SetStatus { id: i32, status: String },
     #[command(about = "Visualize jobs in a web browser.")]
     Serve,
     #[command(about = "Export jobs to a file.")]
     Export(ExportArgs),
+    #[command(about = "Import jobs from a file previously produced by Export.")]
+    Import(ImportArgs),
     #[command(about = "Clear the database")]
     Clear,
+    #[command(about = "Inspect or administer the underlying database directly")]
+    Db {
+        #[command(subcommand)]
+        cmd: DbCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    #[command(about = "Open an interactive SQL shell against the database")]
+    Cli,
+    #[command(about = "Print the resolved database file path")]
+    Path,
+    #[command(about = "Force-run migrations, creating the database file if needed")]
+    Setup,
 }
 
 #[derive(Args, Debug)]
@@ -59,6 +80,23 @@ struct ExportArgs {
     format: Format,
 }
 
+#[derive(Args, Debug)]
+struct ImportArgs {
+    #[arg(long, default_value = "jobs.json", help = "File to import jobs from.")]
+    file: String,
+    #[arg(
+        long,
+        default_value = "json",
+        help = "Format of the imported file. Options: json, csv"
+    )]
+    format: Format,
+    #[arg(
+        long,
+        help = "Update existing jobs with matching ids instead of duplicating them."
+    )]
+    upsert: bool,
+}
+
 #[derive(Debug, Clone)]
 enum Format {
     Json = 1,
@@ -85,9 +123,21 @@ struct SearchArgs {
     description: Option<String>,
     #[arg(long, default_value = "")]
     date: Option<String>,
+    #[arg(long, default_value = "", help = "Only jobs added on or after this date (dd-mm-yyyy)")]
+    after: Option<String>,
+    #[arg(long, default_value = "", help = "Only jobs added on or before this date (dd-mm-yyyy)")]
+    before: Option<String>,
+    #[arg(long, default_value = "", help = "Filter by status: saved, applied, interviewing, offer, rejected")]
+    status: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Ranked full-text search over title and description, e.g. \"rust remote\""
+    )]
+    query: Option<String>,
 }
 
-fn serve_jobs(queries: &db::Queries) {
+fn serve_jobs(db: &DB) {
     let tcp = TcpListener::bind("127.0.0.1:8080")
         .map_err(|e| eprintln!("Error while binding to port: {}", e))
         .unwrap();
@@ -95,12 +145,34 @@ fn serve_jobs(queries: &db::Queries) {
     println!("Listening on http://127.0.0.1:8080");
     for stream in tcp.incoming() {
         match stream {
-            Ok(stream) => handle_request(stream, queries),
+            Ok(stream) => {
+                let db = db.clone();
+                thread::spawn(move || match db.try_get_conn() {
+                    Ok(conn) => {
+                        let queries = db::Queries::new(conn);
+                        handle_request(stream, &queries);
+                    }
+                    Err(e) => {
+                        eprintln!("Error checking out database connection: {}", e);
+                        respond_service_unavailable(stream);
+                    }
+                });
+            }
             Err(e) => eprintln!("Error while accepting connection: {}", e),
         }
     }
 }
 
+fn respond_service_unavailable(mut stream: TcpStream) {
+    let body = "503 Service Unavailable";
+    let response = format!(
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/text\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
 fn handle_request(mut stream: TcpStream, queries: &db::Queries) {
     let mut buf_reader = BufReader::new(&stream);
     let mut content_length = 0;
@@ -144,7 +216,7 @@ fn handle_request(mut stream: TcpStream, queries: &db::Queries) {
         let mut job_list = String::new();
 
         for job in jobs {
-            let date = NaiveDate::parse_from_str(&job.date, "%d-%m-%Y").unwrap();
+            let date = NaiveDate::parse_from_str(&job.date, "%Y-%m-%d").unwrap();
             let delete_button = format!(
                 r#"<form action="/delete/{}" method="POST" class='delete-btn-form'>
                     <button type="submit">Delete</button>
@@ -156,6 +228,7 @@ fn handle_request(mut stream: TcpStream, queries: &db::Queries) {
             job_list.push_str(&format!("<td>{}</td>", job.title));
             job_list.push_str(&format!("<td>{}</td>", job.description));
             job_list.push_str(&format!("<td>{}</td>", date.format("%d/%m/%Y").to_string()));
+            job_list.push_str(&format!("<td>{}</td>", job.status));
             job_list.push_str(&format!("<td>{}</td>", delete_button));
             job_list.push_str("</tr>");
         }
@@ -261,10 +334,94 @@ fn handle_request(mut stream: TcpStream, queries: &db::Queries) {
     }
 }
 
+fn run_sql_repl(db: &DB) {
+    let conn = db.get_conn();
+    let stdin = io::stdin();
+
+    println!("Connected to {}", DB::path());
+    println!("Enter SQL statements, or .exit to quit.");
+
+    loop {
+        print!("sql> ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let sql = line.trim();
+        if sql.is_empty() {
+            continue;
+        }
+        if sql == ".exit" || sql == ".quit" {
+            break;
+        }
+
+        match conn.prepare(sql) {
+            Ok(mut stmt) => {
+                if stmt.column_count() == 0 {
+                    match stmt.execute([]) {
+                        Ok(changes) => println!("{} row(s) affected", changes),
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                } else {
+                    let columns: Vec<String> =
+                        stmt.column_names().into_iter().map(String::from).collect();
+                    let mut table = Table::new();
+                    table.set_titles(Row::new(columns.iter().map(|c| Cell::new(c)).collect()));
+
+                    let mut rows = match stmt.query([]) {
+                        Ok(rows) => rows,
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            continue;
+                        }
+                    };
+
+                    loop {
+                        match rows.next() {
+                            Ok(Some(row)) => {
+                                let cells = (0..columns.len())
+                                    .map(|i| {
+                                        let value: rusqlite::types::Value = row
+                                            .get(i)
+                                            .unwrap_or(rusqlite::types::Value::Null);
+                                        Cell::new(&format_sql_value(&value))
+                                    })
+                                    .collect();
+                                table.add_row(Row::new(cells));
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                break;
+                            }
+                        }
+                    }
+
+                    table.printstd();
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
+
+fn format_sql_value(value: &rusqlite::types::Value) -> String {
+    match value {
+        rusqlite::types::Value::Null => "NULL".to_string(),
+        rusqlite::types::Value::Integer(i) => i.to_string(),
+        rusqlite::types::Value::Real(f) => f.to_string(),
+        rusqlite::types::Value::Text(s) => s.clone(),
+        rusqlite::types::Value::Blob(_) => "<blob>".to_string(),
+    }
+}
+
 fn display_jobs(jobs: Vec<job::Job>) {
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-    table.set_titles(row!["ID", "Title", "Description", "Date"]);
+    table.set_titles(row!["ID", "Title", "Description", "Date", "Status"]);
 
     for job in jobs {
         table.add_row(Row::new(vec![
@@ -272,6 +429,7 @@ fn display_jobs(jobs: Vec<job::Job>) {
             Cell::new(&job.title),
             Cell::new(&job.description),
             Cell::new(&job.date),
+            Cell::new(&job.status.to_string()),
         ]));
     }
     table.printstd();
@@ -279,10 +437,10 @@ fn display_jobs(jobs: Vec<job::Job>) {
 
 fn format_date(date: String) -> String {
     if date == "today" {
-        return Local::now().format("%d-%m-%Y").to_string();
+        return Local::now().format("%Y-%m-%d").to_string();
     } else {
         let formatted_date = match NaiveDate::parse_from_str(&date, "%d-%m-%Y") {
-            Ok(date) => date.format("%d-%m-%Y").to_string(),
+            Ok(date) => date.format("%Y-%m-%d").to_string(),
             Err(_) => "".to_string(),
         };
         if formatted_date.is_empty() {
@@ -298,39 +456,18 @@ fn export_jobs(jobs: Vec<job::Job>, format: Format, file: &str) {
             let f = File::create(file)
                 .map_err(|e| eprintln!("Error opening file: {}", e))
                 .expect("Error opening file");
-            let mut writer = BufWriter::new(f);
-
-            writeln!(writer, "[").expect("Error writing to file");
-
-            for (i, job) in jobs.iter().enumerate() {
-                let job_json = format!(
-                    r#"{{"id": {}, "title": "{}", "description": "{}", "date": "{}"}}"#,
-                    job.id, job.title, job.description, job.date
-                );
-
-                if i < jobs.len() - 1 {
-                    writeln!(writer, "{},", job_json).expect("Error writing job to file");
-                } else {
-                    writeln!(writer, "{}", job_json).expect("Error writing job to file");
-                }
-            }
-
-            writeln!(writer, "]").expect("Error closing JSON array");
+            serde_json::to_writer_pretty(f, &jobs).expect("Error writing jobs to file");
         }
         Format::Csv => {
-            let headers = vec!["id", "title", "description", "date"];
             let mut wtr = csv::Writer::from_path(file)
                 .map_err(|e| eprintln!("Error opening file: {}", e))
                 .unwrap();
-            wtr.write_record(headers)
-                .map_err(|e| eprintln!("Error writing headers: {}", e))
-                .unwrap();
             for job in jobs {
-                let row = vec![job.id.to_string(), job.title, job.description, job.date];
-                wtr.write_record(row)
+                wtr.serialize(job)
                     .map_err(|e| eprintln!("Error writing row: {}", e))
                     .unwrap();
             }
+            wtr.flush().expect("Error flushing csv writer");
         }
     }
     println!("Jobs exported successfully to {}", file);
@@ -341,39 +478,40 @@ fn export_jobs_to_bytes(jobs: Vec<job::Job>, format: Format) -> Vec<u8> {
 
     match format {
         Format::Json => {
-            let mut wtr = BufWriter::new(&mut buf);
-            writeln!(wtr, "[").expect("Error writing to file");
-            for (i, job) in jobs.iter().enumerate() {
-                let job_json = format!(
-                    r#"{{"id": {}, "title": "{}", "description": "{}", "date": "{}"}}"#,
-                    job.id, job.title, job.description, job.date
-                );
-
-                if i < jobs.len() - 1 {
-                    writeln!(wtr, "{},", job_json).expect("Error writing job to file");
-                } else {
-                    writeln!(wtr, "{}", job_json).expect("Error writing job to file");
-                }
-            }
-            writeln!(wtr, "]").expect("Error closing JSON array");
+            serde_json::to_writer_pretty(&mut buf, &jobs).expect("Error writing jobs to file");
         }
         Format::Csv => {
-            let headers = vec!["id", "title", "description", "date"];
             let mut wtr = csv::Writer::from_writer(&mut buf);
-            wtr.write_record(headers)
-                .map_err(|e| eprintln!("Error writing headers: {}", e))
-                .unwrap();
             for job in jobs {
-                let row = vec![job.id.to_string(), job.title, job.description, job.date];
-                wtr.write_record(row)
+                wtr.serialize(job)
                     .map_err(|e| eprintln!("Error writing row: {}", e))
                     .unwrap();
             }
+            wtr.flush().expect("Error flushing csv writer");
         }
     }
     buf
 }
 
+fn import_jobs(format: Format, file: &str) -> Vec<job::Job> {
+    match format {
+        Format::Json => {
+            let f = File::open(file)
+                .map_err(|e| eprintln!("Error opening file: {}", e))
+                .expect("Error opening file");
+            serde_json::from_reader(f).expect("Error reading jobs from file")
+        }
+        Format::Csv => {
+            let mut rdr = csv::Reader::from_path(file)
+                .map_err(|e| eprintln!("Error opening file: {}", e))
+                .unwrap();
+            rdr.deserialize()
+                .map(|row| row.expect("Error reading job from file"))
+                .collect()
+        }
+    }
+}
+
 fn main() {
     let db = DB::new();
     let conn = db.get_conn();
@@ -397,7 +535,17 @@ fn main() {
             println!("Job added successfully");
         }
         Commands::Search(args) => {
-            let jobs = queries.seach_jobs(args.title, args.description, args.date);
+            let jobs = match args.query {
+                Some(query) => queries.fts_search(&query),
+                None => queries.seach_jobs(
+                    args.title,
+                    args.description,
+                    args.date,
+                    args.after,
+                    args.before,
+                    args.status,
+                ),
+            };
             display_jobs(jobs);
         }
         Commands::List => {
@@ -408,17 +556,38 @@ fn main() {
             queries.remove_job(id);
             println!("Job removed successfully");
         }
+        Commands::SetStatus { id, status } => match Status::from_str(&status) {
+            Ok(status) => {
+                queries.update_status(id, status);
+                println!("Job status updated successfully");
+            }
+            Err(e) => eprintln!("{}", e),
+        },
         Commands::Serve => {
-            serve_jobs(&queries);
+            serve_jobs(&db);
         }
         Commands::Export(args) => {
             let jobs = queries.list_jobs();
             export_jobs(jobs, args.format, args.file.as_str());
         }
+        Commands::Import(args) => {
+            let jobs = import_jobs(args.format, args.file.as_str());
+            let count = jobs.len();
+            queries.add_jobs(jobs, args.upsert);
+            println!("Imported {} job(s) from {}", count, args.file);
+        }
         Commands::Clear => {
             db.drop_db();
             println!("Database cleared successfully");
         }
+        Commands::Db { cmd } => match cmd {
+            DbCommands::Cli => run_sql_repl(&db),
+            DbCommands::Path => println!("{}", DB::path()),
+            DbCommands::Setup => {
+                DB::new();
+                println!("Database set up at {}", DB::path());
+            }
+        },
     }
 
     db.close();